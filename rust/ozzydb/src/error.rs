@@ -1,17 +1,115 @@
 use serde::{Deserialize, Serialize};
-use std::fmt::Display;
+use std::{fmt::Display, io::ErrorKind, path::PathBuf};
+
+pub type Result<T> = std::result::Result<T, Error>;
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum Error {
     Abort,
     InvalidData(String),
     InvalidInput(String),
-    IO(String),
+    /// The kind survives serialization if io_kind has a code for it, and
+    /// comes back as ErrorKind::Other otherwise. The io::Error itself is not
+    /// kept as a source, since Error must stay Clone and Serialize; its text
+    /// is in message.
+    IO {
+        #[serde(with = "io_kind")]
+        kind: ErrorKind,
+        message: String,
+    },
     ReadOnly,
     Serialization,
+    /// Wraps another error with where it happened. Use root() to match on
+    /// the wrapped variant.
+    Context {
+        context: Context,
+        source: Box<Error>,
+    },
+}
+
+/// Where an error happened. Any field may be unknown.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Context {
+    pub path: Option<PathBuf>,
+    pub offset: Option<u64>,
+    pub key: Option<Vec<u8>>,
+}
+
+impl Error {
+    /// Stable numeric code for the error, safe to send over the wire.
+    /// Context is transparent: the code is the wrapped error's.
+    pub fn code(&self) -> u16 {
+        match self {
+            Error::Abort => 1,
+            Error::InvalidData(_) => 2,
+            Error::InvalidInput(_) => 3,
+            Error::IO { .. } => 4,
+            Error::ReadOnly => 5,
+            Error::Serialization => 6,
+            Error::Context { source, .. } => source.code(),
+        }
+    }
+
+    /// The error with all Context layers removed.
+    pub fn root(&self) -> &Error {
+        match self {
+            Error::Context { source, .. } => source.root(),
+            err => err,
+        }
+    }
+
+    /// The underlying IO error kind, if this is an IO error.
+    pub fn io_kind(&self) -> Option<ErrorKind> {
+        match self {
+            Error::IO { kind, .. } => Some(*kind),
+            Error::Context { source, .. } => source.io_kind(),
+            _ => None,
+        }
+    }
+
+    pub fn with_path(self, path: impl Into<PathBuf>) -> Self {
+        self.with_context(|c| &mut c.path, path.into())
+    }
+
+    pub fn with_offset(self, offset: u64) -> Self {
+        self.with_context(|c| &mut c.offset, offset)
+    }
+
+    pub fn with_key(self, key: impl Into<Vec<u8>>) -> Self {
+        self.with_context(|c| &mut c.key, key.into())
+    }
+
+    /// Fills in a field of the outermost context. If the error has no
+    /// context, or the field is already set, it is wrapped in a new context
+    /// instead, so inner values (e.g. the segment path) are never lost.
+    fn with_context<T>(self, field: fn(&mut Context) -> &mut Option<T>, value: T) -> Self {
+        let (mut context, source) = match self {
+            Error::Context {
+                mut context,
+                source,
+            } => {
+                if field(&mut context).is_none() {
+                    (context, source)
+                } else {
+                    let inner = Error::Context { context, source };
+                    (Context::default(), Box::new(inner))
+                }
+            }
+            err => (Context::default(), Box::new(err)),
+        };
+        *field(&mut context) = Some(value);
+        Error::Context { context, source }
+    }
 }
 
-impl std::error::Error for Error {}
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Context { source, .. } => Some(source.as_ref()),
+            _ => None,
+        }
+    }
+}
 
 impl Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
@@ -19,9 +117,270 @@ impl Display for Error {
             Error::Abort => write!(f, "operation aborted"),
             Error::InvalidData(msg) => write!(f, "invalid data: {msg}"),
             Error::InvalidInput(msg) => write!(f, "invalid input: {msg}"),
-            Error::IO(msg) => write!(f, "invalid input: {msg}"),
+            Error::IO { message, .. } => write!(f, "io error: {message}"),
             Error::ReadOnly => write!(f, "read-only transaction"),
             Error::Serialization => write!(f, "serialization failure, retry transaction"),
+            Error::Context { context, .. } => write!(f, "at {context}"),
+        }
+    }
+}
+
+/// Prints only the context; the wrapped error is reached via source().
+impl Display for Context {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let mut sep = "";
+        if let Some(path) = &self.path {
+            write!(f, "path {}", path.display())?;
+            sep = ", ";
+        }
+        if let Some(offset) = self.offset {
+            write!(f, "{sep}offset {offset}")?;
+            sep = ", ";
+        }
+        if let Some(key) = &self.key {
+            write!(f, "{sep}key \"{}\"", key.escape_ascii())?;
+        }
+        Ok(())
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Self {
+        Error::IO {
+            kind: err.kind(),
+            message: err.to_string(),
+        }
+    }
+}
+
+/// Encoding failures mean we were handed a value we can't write, while
+/// decoding failures mean the stored bytes are bad.
+impl serde::ser::Error for Error {
+    fn custom<T: Display>(msg: T) -> Self {
+        Error::InvalidInput(msg.to_string())
+    }
+}
+
+impl serde::de::Error for Error {
+    fn custom<T: Display>(msg: T) -> Self {
+        Error::InvalidData(msg.to_string())
+    }
+}
+
+/// Serializes io::ErrorKind as a stable numeric code. Kinds without a code
+/// come back as ErrorKind::Other.
+mod io_kind {
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::io::ErrorKind;
+
+    pub(super) const KINDS: &[(u8, ErrorKind)] = &[
+        (1, ErrorKind::NotFound),
+        (2, ErrorKind::PermissionDenied),
+        (3, ErrorKind::AlreadyExists),
+        (4, ErrorKind::WouldBlock),
+        (5, ErrorKind::InvalidInput),
+        (6, ErrorKind::InvalidData),
+        (7, ErrorKind::TimedOut),
+        (8, ErrorKind::WriteZero),
+        (9, ErrorKind::Interrupted),
+        (10, ErrorKind::Unsupported),
+        (11, ErrorKind::UnexpectedEof),
+        (12, ErrorKind::OutOfMemory),
+        (13, ErrorKind::StorageFull),
+        (14, ErrorKind::ReadOnlyFilesystem),
+        (15, ErrorKind::QuotaExceeded),
+        (16, ErrorKind::FileTooLarge),
+        (17, ErrorKind::ResourceBusy),
+        (18, ErrorKind::Deadlock),
+        (19, ErrorKind::BrokenPipe),
+        (20, ErrorKind::ConnectionRefused),
+        (21, ErrorKind::ConnectionReset),
+        (22, ErrorKind::ConnectionAborted),
+        (23, ErrorKind::NotConnected),
+        (24, ErrorKind::AddrInUse),
+        (25, ErrorKind::AddrNotAvailable),
+        (26, ErrorKind::IsADirectory),
+        (27, ErrorKind::NotADirectory),
+        (28, ErrorKind::DirectoryNotEmpty),
+        (29, ErrorKind::CrossesDevices),
+        (30, ErrorKind::InvalidFilename),
+        (31, ErrorKind::NotSeekable),
+        (32, ErrorKind::TooManyLinks),
+        (33, ErrorKind::StaleNetworkFileHandle),
+        (34, ErrorKind::HostUnreachable),
+        (35, ErrorKind::NetworkUnreachable),
+        (36, ErrorKind::NetworkDown),
+        (37, ErrorKind::ExecutableFileBusy),
+        (38, ErrorKind::ArgumentListTooLong),
+    ];
+    const OTHER: u8 = 0;
+
+    pub fn serialize<S: Serializer>(kind: &ErrorKind, s: S) -> Result<S::Ok, S::Error> {
+        let code = KINDS
+            .iter()
+            .find(|(_, k)| k == kind)
+            .map_or(OTHER, |(code, _)| *code);
+        s.serialize_u8(code)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<ErrorKind, D::Error> {
+        let code = u8::deserialize(d)?;
+        Ok(KINDS
+            .iter()
+            .find(|(c, _)| *c == code)
+            .map_or(ErrorKind::Other, |(_, kind)| *kind))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn codes_are_stable() {
+        let io = Error::from(std::io::Error::from(ErrorKind::NotFound));
+        assert_eq!(Error::Abort.code(), 1);
+        assert_eq!(Error::InvalidData("x".into()).code(), 2);
+        assert_eq!(Error::InvalidInput("x".into()).code(), 3);
+        assert_eq!(io.code(), 4);
+        assert_eq!(Error::ReadOnly.code(), 5);
+        assert_eq!(Error::Serialization.code(), 6);
+        assert_eq!(Error::ReadOnly.with_offset(1).code(), 5);
+    }
+
+    #[test]
+    fn io_kind_codes_are_stable() {
+        let expect = [
+            (1, ErrorKind::NotFound),
+            (2, ErrorKind::PermissionDenied),
+            (3, ErrorKind::AlreadyExists),
+            (4, ErrorKind::WouldBlock),
+            (5, ErrorKind::InvalidInput),
+            (6, ErrorKind::InvalidData),
+            (7, ErrorKind::TimedOut),
+            (8, ErrorKind::WriteZero),
+            (9, ErrorKind::Interrupted),
+            (10, ErrorKind::Unsupported),
+            (11, ErrorKind::UnexpectedEof),
+            (12, ErrorKind::OutOfMemory),
+            (13, ErrorKind::StorageFull),
+            (14, ErrorKind::ReadOnlyFilesystem),
+            (15, ErrorKind::QuotaExceeded),
+            (16, ErrorKind::FileTooLarge),
+            (17, ErrorKind::ResourceBusy),
+            (18, ErrorKind::Deadlock),
+            (19, ErrorKind::BrokenPipe),
+            (20, ErrorKind::ConnectionRefused),
+            (21, ErrorKind::ConnectionReset),
+            (22, ErrorKind::ConnectionAborted),
+            (23, ErrorKind::NotConnected),
+            (24, ErrorKind::AddrInUse),
+            (25, ErrorKind::AddrNotAvailable),
+            (26, ErrorKind::IsADirectory),
+            (27, ErrorKind::NotADirectory),
+            (28, ErrorKind::DirectoryNotEmpty),
+            (29, ErrorKind::CrossesDevices),
+            (30, ErrorKind::InvalidFilename),
+            (31, ErrorKind::NotSeekable),
+            (32, ErrorKind::TooManyLinks),
+            (33, ErrorKind::StaleNetworkFileHandle),
+            (34, ErrorKind::HostUnreachable),
+            (35, ErrorKind::NetworkUnreachable),
+            (36, ErrorKind::NetworkDown),
+            (37, ErrorKind::ExecutableFileBusy),
+            (38, ErrorKind::ArgumentListTooLong),
+        ];
+        assert_eq!(io_kind::KINDS, expect);
+        for (code, kind) in expect {
+            let err = Error::from(std::io::Error::from(kind));
+            let json = serde_json::to_value(&err).unwrap();
+            assert_eq!(json["IO"]["kind"], code);
+            assert_eq!(serde_json::from_value::<Error>(json).unwrap(), err);
+        }
+    }
+
+    #[test]
+    fn context_roundtrip() {
+        let err = Error::from(std::io::Error::from(ErrorKind::StorageFull))
+            .with_path("00001.log")
+            .with_offset(42)
+            .with_key(b"k\x00".to_vec());
+        let json = serde_json::to_string(&err).unwrap();
+        let decoded: Error = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded, err);
+        assert_eq!(decoded.io_kind(), Some(ErrorKind::StorageFull));
+        assert_eq!(decoded.code(), 4);
+    }
+
+    #[test]
+    fn unknown_io_kind_decodes_as_other() {
+        let json = r#"{"IO":{"kind":255,"message":"m"}}"#;
+        let err: Error = serde_json::from_str(json).unwrap();
+        assert_eq!(err.io_kind(), Some(ErrorKind::Other));
+    }
+
+    #[test]
+    fn context_nests_instead_of_overwriting() {
+        let err = Error::InvalidData("bad entry".into())
+            .with_path("segment.log")
+            .with_offset(7)
+            .with_path("store");
+        let Error::Context { context, source } = &err else {
+            panic!("expected context, got {err:?}");
+        };
+        assert_eq!(context.path, Some(PathBuf::from("store")));
+        assert_eq!(context.offset, None);
+        let Error::Context { context, .. } = source.as_ref() else {
+            panic!("expected inner context, got {source:?}");
+        };
+        assert_eq!(context.path, Some(PathBuf::from("segment.log")));
+        assert_eq!(context.offset, Some(7));
+        assert_eq!(err.code(), 2);
+    }
+
+    #[test]
+    fn source_chain_prints_each_message_once() {
+        let err = Error::from(std::io::Error::from(ErrorKind::StorageFull))
+            .with_path("segment.log")
+            .with_offset(7)
+            .with_key(b"k\x00".to_vec())
+            .with_path("store");
+        let mut chain = vec![err.to_string()];
+        let mut source = std::error::Error::source(&err);
+        while let Some(inner) = source {
+            chain.push(inner.to_string());
+            source = inner.source();
         }
+        assert_eq!(
+            chain,
+            [
+                "at path store",
+                "at path segment.log, offset 7, key \"k\\x00\"",
+                "io error: no storage space",
+            ]
+        );
+    }
+
+    #[test]
+    fn root_unwraps_context() {
+        let err = Error::ReadOnly
+            .with_key(b"k".to_vec())
+            .with_key(b"j".to_vec());
+        assert_ne!(err, Error::ReadOnly);
+        assert_eq!(err.root(), &Error::ReadOnly);
+        assert!(matches!(err.root(), Error::ReadOnly));
+        assert_eq!(Error::Abort.root(), &Error::Abort);
+    }
+
+    #[test]
+    fn serde_custom_errors() {
+        assert_eq!(
+            <Error as serde::ser::Error>::custom("x"),
+            Error::InvalidInput("x".into())
+        );
+        assert_eq!(
+            <Error as serde::de::Error>::custom("x"),
+            Error::InvalidData("x".into())
+        );
     }
 }
@@ -1,6 +1,7 @@
 mod error;
 
 use {
+    error::Result,
     log::info,
     std::{collections::BTreeMap, fs::File, path::PathBuf, result::Result as StdResult},
 };